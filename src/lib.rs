@@ -1,5 +1,47 @@
+use regex::Regex;
 use std::collections::{HashMap, HashSet};
 
+/// Identifies a `SegmentMatcher` registered on a `PatternMatcher<T>` via
+/// `add_regex_matcher`/`add_predicate_matcher`, for use with
+/// `Segment::Pred` in `add_pattern_with_matchers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MatcherId(usize);
+
+/// A stable handle to a registered pattern, returned by `add_pattern`/
+/// `add_pattern_with_matchers` and accepted by `remove`. Ids are never
+/// reused, so a handle from a removed (or never-existing) pattern safely
+/// returns `None` instead of silently resolving to an unrelated pattern
+/// the way a reused `Vec` index could.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubscriptionId(u64);
+
+/// A segment-level matcher referenced by `Segment::Pred`: either a compiled
+/// regex or an arbitrary predicate closure.
+pub enum SegmentMatcher {
+    Regex(Regex),
+    Predicate(Box<dyn Fn(&str) -> bool>),
+}
+
+impl SegmentMatcher {
+    fn matches(&self, segment: &str) -> bool {
+        match self {
+            SegmentMatcher::Regex(re) => re.is_match(segment),
+            SegmentMatcher::Predicate(predicate) => predicate(segment),
+        }
+    }
+}
+
+/// A single pattern segment for `add_pattern_with_matchers`, for callers
+/// that want a segment matched by a regex or predicate rather than only the
+/// built-in exact/`*`/`**` forms.
+#[derive(Debug, Clone)]
+pub enum Segment {
+    Exact(String),
+    Star,
+    DoubleStar,
+    Pred(MatcherId),
+}
+
 #[derive(Debug, Default)]
 pub struct TrieNode {
     // Children for exact segment matches (e.g., "stock", "nyse")
@@ -10,164 +52,1093 @@ pub struct TrieNode {
     // Note: '**' must be the last segment in a pattern branch,
     // or intermediate, allowing matches further down.
     double_star_child: Option<Box<TrieNode>>,
-    // Indices into the PatternMatcher's patterns_with_data Vec
-    pattern_indices: Vec<usize>,
+    // Children keyed by a registered SegmentMatcher (regex/predicate). Can't
+    // be keyed in a HashMap like `children`, so matchers are tried linearly
+    // in registration order; keep call sites' matcher sets small and put the
+    // cheapest/most-selective ones first if match cost matters.
+    predicate_children: Vec<(MatcherId, Box<TrieNode>)>,
+    // Ids of patterns terminating at this node, looked up in the
+    // PatternMatcher's `patterns` store.
+    pattern_indices: Vec<SubscriptionId>,
+    // Capture name bound to this node when it was reached via the parent's
+    // `star_child`/`double_star_child` edge using named-wildcard syntax
+    // (e.g. `{exchange}` or `{rest**}`). `None` for plain `*`/`**` segments.
+    wildcard_name: Option<String>,
+}
+
+/// The default cap on work-list frames a single `match_topic` call may push
+/// before giving up with `MatchError::FrameLimitExceeded`. Generous enough
+/// for any realistic pattern set while still bounding memory on a runaway
+/// or adversarial one.
+pub const DEFAULT_MAX_FRAMES: usize = 1 << 20;
+
+/// Errors returned while matching a topic against the pattern set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchError {
+    /// The traversal needed more work-list frames than the configured
+    /// `max_frames` allows. Raise the limit with `with_max_frames` if the
+    /// pattern set is legitimately this deep, or treat it as a signal that
+    /// the pattern set is pathological (e.g. very long `**` chains).
+    FrameLimitExceeded,
+}
+
+impl std::fmt::Display for MatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatchError::FrameLimitExceeded => write!(f, "match_topic exceeded the configured max_frames limit"),
+        }
+    }
+}
+
+impl std::error::Error for MatchError {}
+
+// Work-list frame for the iterative matcher below. `Match` mirrors a call to
+// the old `find_matches_recursive(node, segment_index)`; `CollectAll` mirrors
+// a call to the old `collect_all_terminal_patterns(node)`.
+enum Frame<'a> {
+    Match(&'a TrieNode, usize),
+    CollectAll(&'a TrieNode),
+}
+
+// Work-list frame for the capture-tracking matcher below. Each frame carries
+// its own snapshot of the bindings accumulated along the path that reached
+// it, since distinct branches (e.g. two different '**' consumption lengths)
+// can carry different bindings from the same node — there's no single
+// shared stack to push/pop against the way there is in recursion.
+enum CaptureFrame<'a> {
+    Match { node: &'a TrieNode, segment_index: usize, bindings: Vec<(String, String)> },
+    CollectAll { node: &'a TrieNode, bindings: Vec<(String, String)> },
+}
+
+/// How seriously a caller should treat a `PatternWarning`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Ignore,
+    Warn,
+    Error,
+}
+
+/// A problem found by `PatternMatcher::diagnostics` while analyzing the
+/// pattern set as a whole, rather than any single topic match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternWarning {
+    /// Every topic `pattern` matches is also matched by `subsumed_by`, so
+    /// `pattern` can never fire on its own (e.g. `stock.nyse.ibm.price` when
+    /// `stock.**` is also registered).
+    Redundant { pattern: String, subsumed_by: String },
+    /// `pattern` contains a `**`/`{name**}` segment that is not the last
+    /// segment. In this matcher's semantics a multi-level wildcard absorbs
+    /// every topic segment from that point on regardless of what follows it
+    /// in the pattern, so the segments after it can never actually filter
+    /// anything.
+    Unreachable { pattern: String },
+    /// `pattern` was registered more than once; `indices` lists every
+    /// occurrence's subscription id in insertion order.
+    Duplicate { pattern: String, indices: Vec<SubscriptionId> },
+}
+
+impl PatternWarning {
+    /// The default severity for this warning's kind.
+    pub fn severity(&self) -> Severity {
+        match self {
+            PatternWarning::Redundant { .. } => Severity::Warn,
+            PatternWarning::Unreachable { .. } => Severity::Warn,
+            PatternWarning::Duplicate { .. } => Severity::Error,
+        }
+    }
+}
+
+// Classifies a single pattern segment the same way `add_pattern` does, for
+// code (like `diagnostics`) that needs to reason about a pattern string
+// without walking it into the trie.
+enum SegmentKind<'a> {
+    Exact(&'a str),
+    Star,
+    DoubleStar,
+}
+
+// `single_token`/`multi_token` are the configured spellings of `*`/`**`
+// (e.g. MQTT's `+`/`#`); named-wildcard braces are recognized regardless of
+// the configured alphabet, since they're an orthogonal syntax layer.
+fn classify_segment<'a>(segment: &'a str, single_token: &str, multi_token: &str) -> SegmentKind<'a> {
+    if segment == multi_token {
+        return SegmentKind::DoubleStar;
+    }
+    if segment == single_token {
+        return SegmentKind::Star;
+    }
+    if segment.starts_with('{') && segment.ends_with('}') {
+        let inner = &segment[1..segment.len() - 1];
+        return if inner.ends_with("**") {
+            SegmentKind::DoubleStar
+        } else {
+            SegmentKind::Star
+        };
+    }
+    SegmentKind::Exact(segment)
+}
+
+// The capture name bound by a `{name}`/`{name**}` segment, or `None` for a
+// plain wildcard/exact segment.
+fn named_wildcard_name(segment: &str) -> Option<&str> {
+    if segment.starts_with('{') && segment.ends_with('}') {
+        let inner = &segment[1..segment.len() - 1];
+        Some(inner.strip_suffix("**").unwrap_or(inner))
+    } else {
+        None
+    }
+}
+
+// Renders a `Segment` slice back to a delimiter-joined string, for storing
+// alongside its data in the `patterns` store (so `match_topic` can still
+// return a `&str` for patterns built via `add_pattern_with_matchers`).
+fn describe_segments(segments: &[Segment], delimiter: char) -> String {
+    segments
+        .iter()
+        .map(|segment| match segment {
+            Segment::Exact(exact) => exact.clone(),
+            Segment::Star => "*".to_string(),
+            Segment::DoubleStar => "**".to_string(),
+            Segment::Pred(matcher_id) => format!("<matcher:{}>", matcher_id.0),
+        })
+        .collect::<Vec<_>>()
+        .join(&delimiter.to_string())
+}
+
+// True if every topic `b_pattern` matches is also matched by `a_pattern`,
+// i.e. `a_pattern` subsumes `b_pattern`. Checked by walking `a_pattern`'s
+// segments against `b_pattern`'s, treating a `*`/`**` in `b_pattern` as an
+// opaque token that only a `*`/`**` at the same position in `a_pattern` can
+// cover (an exact segment in `a_pattern` can never subsume a wildcard).
+fn pattern_subsumes(
+    a_pattern: &str,
+    b_pattern: &str,
+    delimiter: char,
+    single_token: &str,
+    multi_token: &str,
+) -> bool {
+    let a: Vec<&str> = a_pattern.split(delimiter).collect();
+    let b: Vec<&str> = b_pattern.split(delimiter).collect();
+    let mut i = 0;
+    let mut j = 0;
+
+    while i < a.len() {
+        match classify_segment(a[i], single_token, multi_token) {
+            // A '**' in a_pattern absorbs everything remaining in b_pattern.
+            SegmentKind::DoubleStar => return true,
+            SegmentKind::Star => {
+                if j >= b.len() {
+                    return false;
+                }
+                // A '*' only subsumes a single concrete segment (or another
+                // '*'); a '**' at this position matches topics of other
+                // lengths too (zero or many segments) that the '*' never
+                // does, so it can't be covered by one.
+                if matches!(classify_segment(b[j], single_token, multi_token), SegmentKind::DoubleStar) {
+                    return false;
+                }
+                i += 1;
+                j += 1;
+            }
+            SegmentKind::Exact(a_seg) => {
+                if j >= b.len() {
+                    return false;
+                }
+                match classify_segment(b[j], single_token, multi_token) {
+                    SegmentKind::Exact(b_seg) if a_seg == b_seg => {
+                        i += 1;
+                        j += 1;
+                    }
+                    _ => return false,
+                }
+            }
+        }
+    }
+
+    i == a.len() && j == b.len()
+}
+
+/// Errors returned while registering a pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    /// `multi_level_terminal_only` is set on the builder and `pattern` uses
+    /// its multi-level wildcard somewhere other than the final segment
+    /// (MQTT's `#` rule).
+    NonTerminalMultiLevelWildcard { pattern: String },
+}
+
+impl std::fmt::Display for PatternError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatternError::NonTerminalMultiLevelWildcard { pattern } => write!(
+                f,
+                "pattern {pattern:?} uses its multi-level wildcard before the final segment, \
+                 which this matcher's multi_level_terminal_only option forbids"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+// True if `node` has no patterns terminating on it and no children of any
+// kind, i.e. it's safe to drop from its parent without losing anything.
+fn is_empty(node: &TrieNode) -> bool {
+    node.pattern_indices.is_empty()
+        && node.children.is_empty()
+        && node.star_child.is_none()
+        && node.double_star_child.is_none()
+        && node.predicate_children.is_empty()
+}
+
+// Descends `path` from `node` without recursion, so a pattern with an
+// unusually large number of segments can't overflow the call stack (the same
+// concern that made `find_matches_iterative` a work-list loop rather than a
+// recursive walk).
+fn descend_mut<'a>(node: &'a mut TrieNode, path: &[Segment]) -> Option<&'a mut TrieNode> {
+    let mut current = node;
+    for segment in path {
+        current = match segment {
+            Segment::Exact(exact) => current.children.get_mut(exact)?,
+            Segment::Star => current.star_child.as_deref_mut()?,
+            Segment::DoubleStar => current.double_star_child.as_deref_mut()?,
+            Segment::Pred(matcher_id) => {
+                let position = current
+                    .predicate_children
+                    .iter()
+                    .position(|(existing_id, _)| existing_id == matcher_id)?;
+                &mut current.predicate_children[position].1
+            }
+        };
+    }
+    Some(current)
+}
+
+// Walks `path` from `node` to the terminal node `id` was registered on,
+// removes `id` from its `pattern_indices`, then prunes every now-empty node
+// back up the path so a long-running matcher doesn't grow monotonically as
+// subscriptions churn. Implemented as two non-recursive passes (descend,
+// then re-descend once per trailing prefix to prune) rather than a single
+// recursive walk, for the same stack-depth reason as `descend_mut`.
+fn remove_from_trie(node: &mut TrieNode, path: &[Segment], id: SubscriptionId) {
+    match descend_mut(node, path) {
+        Some(terminal) => terminal.pattern_indices.retain(|existing| *existing != id),
+        None => return,
+    }
+
+    for depth in (0..path.len()).rev() {
+        let Some(parent) = descend_mut(node, &path[..depth]) else { break };
+        let child_is_empty = match &path[depth] {
+            Segment::Exact(exact) => parent.children.get(exact).map(is_empty),
+            Segment::Star => parent.star_child.as_deref().map(is_empty),
+            Segment::DoubleStar => parent.double_star_child.as_deref().map(is_empty),
+            Segment::Pred(matcher_id) => parent
+                .predicate_children
+                .iter()
+                .find(|(existing_id, _)| existing_id == matcher_id)
+                .map(|(_, child)| is_empty(child)),
+        };
+        if child_is_empty != Some(true) {
+            break;
+        }
+        match &path[depth] {
+            Segment::Exact(exact) => {
+                parent.children.remove(exact);
+            }
+            Segment::Star => parent.star_child = None,
+            Segment::DoubleStar => parent.double_star_child = None,
+            Segment::Pred(matcher_id) => {
+                if let Some(position) = parent
+                    .predicate_children
+                    .iter()
+                    .position(|(existing_id, _)| existing_id == matcher_id)
+                {
+                    parent.predicate_children.remove(position);
+                }
+            }
+        }
+    }
+}
+
+/// Configures the segment delimiter and wildcard token alphabet before
+/// constructing a `PatternMatcher`, so the same trie engine can serve
+/// NATS-style `.`/`*`/`>` subjects, MQTT-style `/`/`+`/`#` topics, or this
+/// crate's own `.`/`*`/`**` default (what `PatternMatcher::new` builds by
+/// calling `PatternMatcherBuilder::new().build()`).
+pub struct PatternMatcherBuilder {
+    delimiter: char,
+    single_level_token: String,
+    multi_level_token: String,
+    multi_level_terminal_only: bool,
+}
+
+impl PatternMatcherBuilder {
+    pub fn new() -> Self {
+        PatternMatcherBuilder {
+            delimiter: '.',
+            single_level_token: "*".to_string(),
+            multi_level_token: "**".to_string(),
+            multi_level_terminal_only: false,
+        }
+    }
+
+    /// Sets the character patterns and topics are split on. Default: `.`.
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Sets the token that denotes a single-level wildcard. Default: `*`.
+    pub fn single_level_token(mut self, token: impl Into<String>) -> Self {
+        self.single_level_token = token.into();
+        self
+    }
+
+    /// Sets the token that denotes a multi-level wildcard. Default: `**`.
+    pub fn multi_level_token(mut self, token: impl Into<String>) -> Self {
+        self.multi_level_token = token.into();
+        self
+    }
+
+    /// When set, `add_pattern` rejects patterns where the multi-level
+    /// wildcard isn't the final segment (MQTT's `#` rule) instead of
+    /// silently accepting it. Default: `false`.
+    pub fn multi_level_terminal_only(mut self, value: bool) -> Self {
+        self.multi_level_terminal_only = value;
+        self
+    }
+
+    pub fn build<T>(self) -> PatternMatcher<T> {
+        PatternMatcher {
+            root: TrieNode::default(),
+            patterns: HashMap::new(),
+            next_subscription_id: 0,
+            matchers: Vec::new(),
+            max_frames: DEFAULT_MAX_FRAMES,
+            delimiter: self.delimiter,
+            single_token: self.single_level_token,
+            multi_token: self.multi_level_token,
+            multi_level_terminal_only: self.multi_level_terminal_only,
+        }
+    }
+}
+
+/// A single `match_topic_with_captures` result: the matched pattern string,
+/// its associated data, and the bindings collected along the path that
+/// matched it.
+pub type CapturedMatch<'a, T> = (&'a str, &'a T, HashMap<String, String>);
+
+// Bindings collected for a single matching path, paired with the pattern
+// index it matched — the capture-tracking counterpart of `HashSet<SubscriptionId>`
+// in `find_matches_iterative`, before the ids are resolved back to data.
+type CaptureHit = (SubscriptionId, HashMap<String, String>);
+
+impl Default for PatternMatcherBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-#[derive(Default)]
 pub struct PatternMatcher<T> { // Make struct generic over T
     root: TrieNode,
-    patterns_with_data: Vec<(String, T)>, // Store (pattern_string, associated_data)
+    // Generation-stable store of (pattern_string, trie path, associated_data),
+    // keyed by the SubscriptionId handed back from add_pattern/
+    // add_pattern_with_matchers. A HashMap (rather than a Vec indexed by
+    // insertion order) means removing one pattern never shifts another's id.
+    patterns: HashMap<SubscriptionId, (String, Vec<Segment>, T)>,
+    next_subscription_id: u64,
+    matchers: Vec<SegmentMatcher>, // Registered regex/predicate matchers, indexed by MatcherId
+    max_frames: usize,
+    delimiter: char,
+    single_token: String,
+    multi_token: String,
+    multi_level_terminal_only: bool,
+}
+
+impl<T> Default for PatternMatcher<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // Implement methods for the generic PatternMatcher<T>
 impl<T> PatternMatcher<T> {
+    /// Creates a matcher using this crate's own `.`/`*`/`**` alphabet. Use
+    /// `PatternMatcherBuilder` instead to configure a different delimiter or
+    /// wildcard tokens (e.g. MQTT's `/`/`+`/`#`).
     pub fn new() -> Self {
-        PatternMatcher {
-            root: TrieNode::default(),
-            patterns_with_data: Vec::new(),
-        }
+        PatternMatcherBuilder::new().build()
+    }
+
+    /// Sets the maximum number of work-list frames `match_topic` may push
+    /// before failing with `MatchError::FrameLimitExceeded`, instead of the
+    /// `DEFAULT_MAX_FRAMES` default.
+    pub fn with_max_frames(mut self, max_frames: usize) -> Self {
+        self.max_frames = max_frames;
+        self
+    }
+
+    // Mints the next SubscriptionId. Ids are never reused, even after
+    // `remove`, so a stale handle can never resolve to a different pattern.
+    fn next_id(&mut self) -> SubscriptionId {
+        let id = SubscriptionId(self.next_subscription_id);
+        self.next_subscription_id += 1;
+        id
     }
 
     /// Adds a subscription pattern and its associated data to the matcher.
-    pub fn add_pattern(&mut self, pattern: &str, data: T) { // Accept data T
+    ///
+    /// A segment may also be a named wildcard: `{name}` binds the single
+    /// concrete segment matched at that position, and `{name**}` binds the
+    /// (possibly multi-segment) slice consumed by a multi-level wildcard.
+    /// These behave exactly like the configured single-/multi-level tokens
+    /// for matching purposes; the name is only used to label captures
+    /// returned by `match_topic_with_captures`.
+    ///
+    /// Returns `Err(PatternError::NonTerminalMultiLevelWildcard)` if the
+    /// matcher was built with `multi_level_terminal_only` and `pattern`'s
+    /// multi-level wildcard isn't its final segment.
+    ///
+    /// On success, returns the pattern's `SubscriptionId`, which can later be
+    /// passed to `remove` to unsubscribe it.
+    pub fn add_pattern(&mut self, pattern: &str, data: T) -> Result<SubscriptionId, PatternError> { // Accept data T
         if pattern.is_empty() {
-            return; // Or handle as needed
+            // Nothing to register; mint an id anyway so the return type stays
+            // uniform, but it resolves to nothing and `remove` on it is a no-op.
+            return Ok(self.next_id());
         }
 
-        // Store the pattern and data, get its index
-        let pattern_index = self.patterns_with_data.len();
-        self.patterns_with_data.push((pattern.to_string(), data));
+        let segments: Vec<&str> = pattern.split(self.delimiter).collect();
+        let last = segments.len() - 1;
+
+        if self.multi_level_terminal_only {
+            for (i, segment) in segments.iter().enumerate() {
+                let is_double_star = matches!(
+                    classify_segment(segment, &self.single_token, &self.multi_token),
+                    SegmentKind::DoubleStar
+                );
+                if i != last && is_double_star {
+                    return Err(PatternError::NonTerminalMultiLevelWildcard {
+                        pattern: pattern.to_string(),
+                    });
+                }
+            }
+        }
 
-        let segments: Vec<&str> = pattern.split('.').collect();
+        let id = self.next_id();
         let mut current_node = &mut self.root;
+        let mut path: Vec<Segment> = Vec::with_capacity(segments.len());
 
-        for (i, segment) in segments.iter().enumerate() {
-            match *segment {
-                "*" => {
-                    current_node = current_node.star_child.get_or_insert_with(Default::default);
+        for segment in &segments {
+            match classify_segment(segment, &self.single_token, &self.multi_token) {
+                SegmentKind::Star => {
+                    let node = current_node.star_child.get_or_insert_with(Default::default);
+                    if let Some(name) = named_wildcard_name(segment) {
+                        node.wildcard_name = Some(name.to_string());
+                    }
+                    current_node = node;
+                    path.push(Segment::Star);
                 }
-                "**" => {
-                    if i != segments.len() - 1 {
-                         // Allow intermediate '**' structurally
+                SegmentKind::DoubleStar => {
+                    let node = current_node.double_star_child.get_or_insert_with(Default::default);
+                    if let Some(name) = named_wildcard_name(segment) {
+                        node.wildcard_name = Some(name.to_string());
                     }
-                    current_node = current_node.double_star_child.get_or_insert_with(Default::default);
+                    current_node = node;
+                    path.push(Segment::DoubleStar);
                 }
-                exact => {
+                SegmentKind::Exact(exact) => {
                     current_node = current_node.children.entry(exact.to_string()).or_default();
+                    path.push(Segment::Exact(exact.to_string()));
                 }
             }
         }
-        // Mark the end of the pattern using its index
-        current_node.pattern_indices.push(pattern_index);
+
+        // Store the pattern, its trie path, and its data, keyed by the id
+        // minted above.
+        current_node.pattern_indices.push(id);
+        self.patterns.insert(id, (pattern.to_string(), path, data));
+        Ok(id)
+    }
+
+    /// Registers a compiled regex as a segment matcher and returns the
+    /// `MatcherId` to reference it from `Segment::Pred` in
+    /// `add_pattern_with_matchers` (e.g. `<regex:^[A-Z]{1,4}$>`-style
+    /// segments).
+    pub fn add_regex_matcher(&mut self, pattern: &str) -> Result<MatcherId, regex::Error> {
+        let regex = Regex::new(pattern)?;
+        let id = MatcherId(self.matchers.len());
+        self.matchers.push(SegmentMatcher::Regex(regex));
+        Ok(id)
+    }
+
+    /// Registers an arbitrary predicate closure as a segment matcher and
+    /// returns the `MatcherId` to reference it from `Segment::Pred`.
+    pub fn add_predicate_matcher<F>(&mut self, predicate: F) -> MatcherId
+    where
+        F: Fn(&str) -> bool + 'static,
+    {
+        let id = MatcherId(self.matchers.len());
+        self.matchers.push(SegmentMatcher::Predicate(Box::new(predicate)));
+        id
+    }
+
+    /// Like `add_pattern`, but lets segments reference a registered
+    /// `SegmentMatcher` (regex or predicate) via `Segment::Pred`, in
+    /// addition to the built-in `Exact`/`Star`/`DoubleStar` forms.
+    ///
+    /// Returns `Err(PatternError::NonTerminalMultiLevelWildcard)` under the
+    /// same `multi_level_terminal_only` rule as `add_pattern`.
+    ///
+    /// On success, returns the pattern's `SubscriptionId`, which can later be
+    /// passed to `remove` to unsubscribe it.
+    pub fn add_pattern_with_matchers(&mut self, segments: &[Segment], data: T) -> Result<SubscriptionId, PatternError> {
+        if segments.is_empty() {
+            return Ok(self.next_id());
+        }
+
+        let pattern_repr = describe_segments(segments, self.delimiter);
+        let last = segments.len() - 1;
+
+        if self.multi_level_terminal_only {
+            for (i, segment) in segments.iter().enumerate() {
+                if i != last && matches!(segment, Segment::DoubleStar) {
+                    return Err(PatternError::NonTerminalMultiLevelWildcard { pattern: pattern_repr });
+                }
+            }
+        }
+
+        let id = self.next_id();
+        let mut current_node = &mut self.root;
+        for segment in segments {
+            match segment {
+                Segment::Exact(exact) => {
+                    current_node = current_node.children.entry(exact.clone()).or_default();
+                }
+                Segment::Star => {
+                    current_node = current_node.star_child.get_or_insert_with(Default::default);
+                }
+                Segment::DoubleStar => {
+                    current_node = current_node.double_star_child.get_or_insert_with(Default::default);
+                }
+                Segment::Pred(matcher_id) => {
+                    let position = current_node
+                        .predicate_children
+                        .iter()
+                        .position(|(existing_id, _)| existing_id == matcher_id);
+                    let position = position.unwrap_or_else(|| {
+                        current_node.predicate_children.push((*matcher_id, Box::default()));
+                        current_node.predicate_children.len() - 1
+                    });
+                    current_node = &mut current_node.predicate_children[position].1;
+                }
+            }
+        }
+
+        current_node.pattern_indices.push(id);
+        self.patterns.insert(id, (pattern_repr, segments.to_vec(), data));
+        Ok(id)
+    }
+
+    /// Unsubscribes the pattern registered under `id`, returning its
+    /// associated data, and prunes the now-unused trie nodes along its path.
+    ///
+    /// `id` is never reused, so calling `remove` again with the same id (or
+    /// any id that was never returned by `add_pattern`/
+    /// `add_pattern_with_matchers`) returns `None` rather than removing an
+    /// unrelated pattern.
+    pub fn remove(&mut self, id: SubscriptionId) -> Option<T> {
+        let (_pattern, path, data) = self.patterns.remove(&id)?;
+        remove_from_trie(&mut self.root, &path, id);
+        Some(data)
     }
 
     /// Finds all patterns that match the given topic and returns pairs of (pattern, data).
-    pub fn match_topic(&self, topic: &str) -> Vec<(&str, &T)> { // Return Vec<(&str, &T)>
+    ///
+    /// Returns `Err(MatchError::FrameLimitExceeded)` if the traversal needs
+    /// more than `max_frames` work-list frames (see `with_max_frames`) — this
+    /// replaces unbounded call-stack recursion, so a pathological pattern set
+    /// (e.g. very long `**` chains) fails gracefully instead of exhausting
+    /// the stack or the heap.
+    pub fn match_topic(&self, topic: &str) -> Result<Vec<(&str, &T)>, MatchError> {
         if topic.is_empty() {
-            return vec![];
+            return Ok(vec![]);
         }
 
-        let segments: Vec<&str> = topic.split('.').collect();
-        let mut matched_indices = HashSet::new(); // Still collect indices
+        let segments: Vec<&str> = topic.split(self.delimiter).collect();
+        let matched_ids = self.find_matches_iterative(&segments)?;
+
+        // Resolve ids back to (pattern string, data) references
+        Ok(matched_ids
+            .into_iter()
+            .filter_map(|id| self.patterns.get(&id))
+            .map(|(pattern_str, _path, data)| (pattern_str.as_str(), data)) // Return refs: (&str, &T)
+            .collect())
+    }
+
+    /// Like `match_topic`, but also returns the concrete segment values bound
+    /// by any named wildcards (`{name}` / `{name**}`) along the path that
+    /// matched each pattern.
+    ///
+    /// Unlike `match_topic`, results are *not* deduplicated into a set: the
+    /// same pattern index can be reached via more than one path through the
+    /// trie (e.g. a `**` that consumes a different number of segments on
+    /// each attempt), and each path can produce different captures, so every
+    /// matching path contributes its own entry.
+    ///
+    /// Returns `Err(MatchError::FrameLimitExceeded)` under the same
+    /// conditions as `match_topic` — see `with_max_frames`.
+    pub fn match_topic_with_captures(&self, topic: &str) -> Result<Vec<CapturedMatch<'_, T>>, MatchError> {
+        if topic.is_empty() {
+            return Ok(vec![]);
+        }
 
-        // Start the recursive search (logic remains the same)
-        self.find_matches_recursive(&self.root, &segments, 0, &mut matched_indices);
+        let segments: Vec<&str> = topic.split(self.delimiter).collect();
+        let hits = self.find_matches_with_captures(&segments)?;
 
-        // Convert indices back to (pattern string, data) references
-        matched_indices
+        Ok(hits
             .into_iter()
-            .map(|index| {
-                let (pattern_str, data) = &self.patterns_with_data[index];
-                (pattern_str.as_str(), data) // Return refs: (&str, &T)
+            .filter_map(|(id, captures)| {
+                let (pattern_str, _path, data) = self.patterns.get(&id)?;
+                Some((pattern_str.as_str(), data, captures))
             })
-            .collect()
+            .collect())
     }
 
-    // Recursive helper function for matching - signature stays the same
-    // It only populates matched_indices (Vec<usize>)
-    fn find_matches_recursive(
-        &self,
-        node: &TrieNode,
-        segments: &[&str],
-        segment_index: usize,
-        matched_indices: &mut HashSet<usize>,
-    ) {
-         // --- Match patterns involving '**' ---
-        if let Some(ds_child) = &node.double_star_child {
-            // 1. '**' matches everything from current segment_index onwards.
-            self.collect_all_terminal_patterns(ds_child, matched_indices);
-
-            // 2. '**' matches zero or more segments, then the rest of the pattern.
-            if segment_index < segments.len() {
-                 self.find_matches_recursive(ds_child, segments, segment_index, matched_indices);
-            }
-             // Case: Pattern like "a.**" matching topic "a"
-             // If the topic ends exactly where '**' begins in the pattern.
-             else if segment_index == segments.len() {
-                 self.collect_all_terminal_patterns(ds_child, matched_indices);
-             }
+    /// Analyzes the registered pattern set and reports redundant,
+    /// unreachable, and duplicate patterns, so callers can surface these at
+    /// registration time instead of discovering them from surprising match
+    /// results later. Each `PatternWarning` carries a `severity()` the
+    /// caller can use to decide whether to ignore, warn, or error.
+    pub fn diagnostics(&self) -> Vec<PatternWarning> {
+        let mut warnings = Vec::new();
+
+        // Iterate the pattern store in a deterministic (id) order so the
+        // results don't depend on the HashMap's iteration order.
+        let mut by_id: Vec<(&SubscriptionId, &String)> =
+            self.patterns.iter().map(|(id, (pattern, _path, _data))| (id, pattern)).collect();
+        by_id.sort_by_key(|(id, _)| **id);
+
+        // Duplicate: the exact same pattern string registered more than once.
+        let mut by_pattern: HashMap<&str, Vec<SubscriptionId>> = HashMap::new();
+        for (id, pattern) in &by_id {
+            by_pattern.entry(pattern.as_str()).or_default().push(**id);
+        }
+        let mut duplicates: Vec<(&str, Vec<SubscriptionId>)> =
+            by_pattern.into_iter().filter(|(_, ids)| ids.len() > 1).collect();
+        duplicates.sort_by_key(|(pattern, _)| pattern.to_string());
+        for (pattern, mut ids) in duplicates {
+            ids.sort();
+            warnings.push(PatternWarning::Duplicate { pattern: pattern.to_string(), indices: ids });
         }
 
-        // --- Base Case: End of topic reached ---
-        if segment_index == segments.len() {
-            // Add patterns ending exactly at this node
-            matched_indices.extend(node.pattern_indices.iter().cloned());
+        // Unreachable: a '**'/'{name**}' segment that isn't the last segment.
+        for (_, pattern) in &by_id {
+            let segments: Vec<&str> = pattern.split(self.delimiter).collect();
+            let last = segments.len() - 1;
+            let has_intermediate_double_star = segments.iter().enumerate().any(|(i, seg)| {
+                i != last && matches!(classify_segment(seg, &self.single_token, &self.multi_token), SegmentKind::DoubleStar)
+            });
+            if has_intermediate_double_star {
+                warnings.push(PatternWarning::Unreachable { pattern: pattern.to_string() });
+            }
+        }
 
-            // Also, if a pattern ending in '**' led here, that '**' matches zero
-            // remaining segments. Check the double_star_child's patterns.
-            // This case is subtly handled by the collect_all_terminal_patterns call
-            // at the beginning of the function if the '**' node was reached *before*
-            // exhausting the topic segments. If we arrive *at* the end of the topic
-            // and the current node has a '**' child, that '**' child represents patterns
-            // ending in '**' which should match.
-             if let Some(ds_child) = &node.double_star_child {
-                  // Add patterns ending *exactly* at the double star node itself.
-                  // Patterns deeper within the double_star tree were handled by collect_all_terminal_patterns
-                  // at the top if ds_child existed.
-                 matched_indices.extend(ds_child.pattern_indices.iter().cloned());
-             }
-            return;
+        // Redundant: one pattern's matched-topic set is a subset of another's.
+        for (i, pattern_b) in &by_id {
+            for (j, pattern_a) in &by_id {
+                if i == j || pattern_a == pattern_b {
+                    continue;
+                }
+                if pattern_subsumes(pattern_a, pattern_b, self.delimiter, &self.single_token, &self.multi_token) {
+                    warnings.push(PatternWarning::Redundant {
+                        pattern: pattern_b.to_string(),
+                        subsumed_by: pattern_a.to_string(),
+                    });
+                    break;
+                }
+            }
         }
 
+        warnings
+    }
+
+    // Iterative counterpart of the old find_matches_recursive/
+    // collect_all_terminal_patterns pair. Both traversals are folded into a
+    // single explicit work-list loop over `Frame` so that topics/patterns
+    // with hundreds of segments (or pathological '**' chains) are bounded by
+    // heap memory instead of call-stack depth. The push order and the cases
+    // handled per frame mirror the recursive version exactly; only the
+    // mechanism (loop + Vec instead of call stack) changed.
+    fn find_matches_iterative(&self, segments: &[&str]) -> Result<HashSet<SubscriptionId>, MatchError> {
+        let mut matched_indices = HashSet::new();
+        let mut stack = vec![Frame::Match(&self.root, 0)];
+        let mut frames_used: usize = 0;
 
-        // --- Recursive Step: Match current segment ---
-        let current_segment = segments[segment_index];
+        while let Some(frame) = stack.pop() {
+            frames_used += 1;
+            if frames_used > self.max_frames {
+                return Err(MatchError::FrameLimitExceeded);
+            }
 
-        // 1. Match exact segment
-        if let Some(child) = node.children.get(current_segment) {
-            self.find_matches_recursive(child, segments, segment_index + 1, matched_indices);
-        }
+            match frame {
+                Frame::CollectAll(node) => {
+                    // Add patterns ending at this node
+                    matched_indices.extend(node.pattern_indices.iter().cloned());
 
-        // 2. Match single-level wildcard '*'
-        if let Some(star_child) = &node.star_child {
-            self.find_matches_recursive(star_child, segments, segment_index + 1, matched_indices);
+                    // Explore children
+                    for child in node.children.values() {
+                        stack.push(Frame::CollectAll(child));
+                    }
+                    if let Some(star_child) = &node.star_child {
+                        stack.push(Frame::CollectAll(star_child));
+                    }
+                    if let Some(ds_child) = &node.double_star_child {
+                        stack.push(Frame::CollectAll(ds_child));
+                    }
+                    for (_, child) in &node.predicate_children {
+                        stack.push(Frame::CollectAll(child));
+                    }
+                }
+
+                Frame::Match(node, segment_index) => {
+                    // --- Match patterns involving '**' ---
+                    if let Some(ds_child) = &node.double_star_child {
+                        // 1. '**' matches everything from current segment_index onwards.
+                        stack.push(Frame::CollectAll(ds_child));
+
+                        // 2. '**' matches zero or more segments, then the rest of the pattern.
+                        if segment_index < segments.len() {
+                            stack.push(Frame::Match(ds_child, segment_index));
+                        }
+                        // Case: Pattern like "a.**" matching topic "a"
+                        // If the topic ends exactly where '**' begins in the pattern.
+                        else if segment_index == segments.len() {
+                            stack.push(Frame::CollectAll(ds_child));
+                        }
+                    }
+
+                    // --- Base Case: End of topic reached ---
+                    if segment_index == segments.len() {
+                        // Add patterns ending exactly at this node
+                        matched_indices.extend(node.pattern_indices.iter().cloned());
+
+                        // Also, if a pattern ending in '**' led here, that '**' matches zero
+                        // remaining segments. Check the double_star_child's patterns directly
+                        // (patterns deeper within the double_star tree were handled by the
+                        // CollectAll frame pushed above, if ds_child existed).
+                        if let Some(ds_child) = &node.double_star_child {
+                            matched_indices.extend(ds_child.pattern_indices.iter().cloned());
+                        }
+                        continue;
+                    }
+
+                    // --- Match current segment ---
+                    let current_segment = segments[segment_index];
+
+                    // 1. Match exact segment
+                    if let Some(child) = node.children.get(current_segment) {
+                        stack.push(Frame::Match(child, segment_index + 1));
+                    }
+
+                    // 2. Match single-level wildcard '*'
+                    if let Some(star_child) = &node.star_child {
+                        stack.push(Frame::Match(star_child, segment_index + 1));
+                    }
+
+                    // 3. Match multi-level wildcard '**' (already handled above)
+
+                    // 4. Match regex/predicate children, tried linearly in
+                    // registration order (can't be keyed in a HashMap).
+                    for (matcher_id, child) in &node.predicate_children {
+                        if self.matchers[matcher_id.0].matches(current_segment) {
+                            stack.push(Frame::Match(child, segment_index + 1));
+                        }
+                    }
+                }
+            }
         }
 
-        // 3. Match multi-level wildcard '**' (already handled at the start of the function)
-        // The logic at the start covers the '**' matching one or more segments.
+        Ok(matched_indices)
     }
 
-
-    // Helper to collect all pattern indices in the subtree rooted at 'node'
-    // Signature stays the same, works with indices.
-    fn collect_all_terminal_patterns(
+    // Iterative, capture-tracking counterpart of `find_matches_iterative`.
+    // Mirrors its control flow (and `Frame`/`CaptureFrame` push order) exactly,
+    // but each frame also carries the (name, value) bindings accumulated for
+    // any named wildcards traversed on the path that produced it, and every
+    // pattern index found is recorded alongside a snapshot of those bindings
+    // instead of being deduplicated into a `HashSet`. Bounded by `max_frames`
+    // the same way, so a pathological pattern set fails with
+    // `MatchError::FrameLimitExceeded` instead of overflowing the call stack.
+    fn find_matches_with_captures(
         &self,
-        node: &TrieNode,
-        matched_indices: &mut HashSet<usize>,
-    ) {
-        // Add patterns ending at this node
-        matched_indices.extend(node.pattern_indices.iter().cloned());
+        segments: &[&str],
+    ) -> Result<Vec<CaptureHit>, MatchError> {
+        let mut hits = Vec::new();
+        let mut stack = vec![CaptureFrame::Match { node: &self.root, segment_index: 0, bindings: Vec::new() }];
+        let mut frames_used: usize = 0;
 
-        // Recursively explore children
-        for child in node.children.values() {
-            self.collect_all_terminal_patterns(child, matched_indices);
-        }
-        if let Some(star_child) = &node.star_child {
-            self.collect_all_terminal_patterns(star_child, matched_indices);
-        }
-         if let Some(ds_child) = &node.double_star_child {
-            self.collect_all_terminal_patterns(ds_child, matched_indices);
+        while let Some(frame) = stack.pop() {
+            frames_used += 1;
+            if frames_used > self.max_frames {
+                return Err(MatchError::FrameLimitExceeded);
+            }
+
+            match frame {
+                CaptureFrame::CollectAll { node, bindings } => {
+                    for idx in &node.pattern_indices {
+                        hits.push((*idx, bindings.iter().cloned().collect()));
+                    }
+
+                    for child in node.children.values() {
+                        stack.push(CaptureFrame::CollectAll { node: child, bindings: bindings.clone() });
+                    }
+                    if let Some(star_child) = &node.star_child {
+                        stack.push(CaptureFrame::CollectAll { node: star_child, bindings: bindings.clone() });
+                    }
+                    if let Some(ds_child) = &node.double_star_child {
+                        stack.push(CaptureFrame::CollectAll { node: ds_child, bindings: bindings.clone() });
+                    }
+                    for (_, child) in &node.predicate_children {
+                        stack.push(CaptureFrame::CollectAll { node: child, bindings: bindings.clone() });
+                    }
+                }
+
+                CaptureFrame::Match { node, segment_index, bindings } => {
+                    if let Some(ds_child) = &node.double_star_child {
+                        // The name (if any) binds the joined slice of segments
+                        // this '**' absorbs from segment_index onward.
+                        let mut ds_bindings = bindings.clone();
+                        if let Some(name) = &ds_child.wildcard_name {
+                            ds_bindings.push((name.clone(), segments[segment_index..].join(&self.delimiter.to_string())));
+                        }
+
+                        // 1. '**' matches everything from current segment_index
+                        // onwards (including zero segments, i.e. landing
+                        // exactly on ds_child). This already covers ds_child's
+                        // own pattern_indices, so there's nothing left for the
+                        // segment_index == segments.len() case below to add.
+                        stack.push(CaptureFrame::CollectAll { node: ds_child, bindings: ds_bindings.clone() });
+
+                        // 2. '**' matches one or more segments, then the rest
+                        // of the pattern.
+                        if segment_index < segments.len() {
+                            stack.push(CaptureFrame::Match { node: ds_child, segment_index, bindings: ds_bindings });
+                        }
+                    }
+
+                    if segment_index == segments.len() {
+                        for idx in &node.pattern_indices {
+                            hits.push((*idx, bindings.iter().cloned().collect()));
+                        }
+                        continue;
+                    }
+
+                    let current_segment = segments[segment_index];
+
+                    if let Some(child) = node.children.get(current_segment) {
+                        stack.push(CaptureFrame::Match { node: child, segment_index: segment_index + 1, bindings: bindings.clone() });
+                    }
+
+                    if let Some(star_child) = &node.star_child {
+                        let mut star_bindings = bindings.clone();
+                        if let Some(name) = &star_child.wildcard_name {
+                            star_bindings.push((name.clone(), current_segment.to_string()));
+                        }
+                        stack.push(CaptureFrame::Match { node: star_child, segment_index: segment_index + 1, bindings: star_bindings });
+                    }
+
+                    for (matcher_id, child) in &node.predicate_children {
+                        if self.matchers[matcher_id.0].matches(current_segment) {
+                            stack.push(CaptureFrame::Match { node: child, segment_index: segment_index + 1, bindings: bindings.clone() });
+                        }
+                    }
+                }
+            }
         }
+
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_segment_double_star_capture_is_reported_once() {
+        let mut matcher: PatternMatcher<u32> = PatternMatcher::new();
+        matcher.add_pattern("a.{rest**}", 1).unwrap();
+
+        let matches = matcher.match_topic_with_captures("a").unwrap();
+
+        assert_eq!(matches.len(), 1, "expected exactly one hit, got {:?}", matches);
+        let (pattern, data, captures) = &matches[0];
+        assert_eq!(*pattern, "a.{rest**}");
+        assert_eq!(**data, 1);
+        assert_eq!(captures.get("rest"), Some(&String::new()));
+    }
+
+    #[test]
+    fn match_topic_with_captures_handles_deep_topics_without_overflowing_the_stack() {
+        let mut matcher: PatternMatcher<u32> = PatternMatcher::new();
+        matcher.add_pattern("{first}.**", 1).unwrap();
+
+        let deep_topic = (0..200_000).map(|i| i.to_string()).collect::<Vec<_>>().join(".");
+        let matches = matcher.match_topic_with_captures(&deep_topic).unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].2.get("first"), Some(&"0".to_string()));
+    }
+
+    #[test]
+    fn match_topic_with_captures_reports_frame_limit_exceeded_instead_of_panicking() {
+        let mut matcher: PatternMatcher<u32> = PatternMatcher::new().with_max_frames(10);
+        let chain = (0..1_000).map(|i| i.to_string()).collect::<Vec<_>>().join(".");
+        matcher.add_pattern(&chain, 1).unwrap();
+
+        let result = matcher.match_topic_with_captures(&chain);
+
+        assert_eq!(result, Err(MatchError::FrameLimitExceeded));
+    }
+
+    #[test]
+    fn match_topic_handles_deep_topics_without_overflowing_the_stack() {
+        let mut matcher: PatternMatcher<u32> = PatternMatcher::new();
+        matcher.add_pattern("*.**", 1).unwrap();
+
+        let deep_topic = (0..200_000).map(|i| i.to_string()).collect::<Vec<_>>().join(".");
+        let matches = matcher.match_topic(&deep_topic).unwrap();
+
+        assert_eq!(matches, vec![("*.**", &1)]);
+    }
+
+    #[test]
+    fn match_topic_reports_frame_limit_exceeded_instead_of_panicking() {
+        let mut matcher: PatternMatcher<u32> = PatternMatcher::new().with_max_frames(10);
+        let chain = (0..1_000).map(|i| i.to_string()).collect::<Vec<_>>().join(".");
+        matcher.add_pattern(&chain, 1).unwrap();
+
+        let result = matcher.match_topic(&chain);
+
+        assert_eq!(result, Err(MatchError::FrameLimitExceeded));
+    }
+
+    #[test]
+    fn diagnostics_flags_redundant_unreachable_and_duplicate_patterns() {
+        let mut matcher: PatternMatcher<u32> = PatternMatcher::new();
+        matcher.add_pattern("stock.**", 1).unwrap();
+        matcher.add_pattern("stock.nyse.ibm.price", 2).unwrap();
+        matcher.add_pattern("a.**.b", 3).unwrap();
+        let dup_a = matcher.add_pattern("x.y", 4).unwrap();
+        let dup_b = matcher.add_pattern("x.y", 5).unwrap();
+
+        let warnings = matcher.diagnostics();
+
+        assert!(warnings.contains(&PatternWarning::Redundant {
+            pattern: "stock.nyse.ibm.price".to_string(),
+            subsumed_by: "stock.**".to_string(),
+        }));
+        assert!(warnings.contains(&PatternWarning::Unreachable { pattern: "a.**.b".to_string() }));
+        assert!(warnings.iter().any(|w| matches!(
+            w,
+            PatternWarning::Duplicate { pattern, indices }
+                if pattern == "x.y" && indices == &vec![dup_a, dup_b]
+        )));
+        assert!(!warnings.contains(&PatternWarning::Redundant {
+            pattern: "stock.**".to_string(),
+            subsumed_by: "stock.nyse.ibm.price".to_string(),
+        }));
+    }
+
+    #[test]
+    fn diagnostics_does_not_claim_a_single_wildcard_subsumes_a_multi_level_one() {
+        let mut matcher: PatternMatcher<u32> = PatternMatcher::new();
+        matcher.add_pattern("x.*", 1).unwrap();
+        matcher.add_pattern("x.**", 2).unwrap();
+
+        let warnings = matcher.diagnostics();
+
+        assert!(!warnings.contains(&PatternWarning::Redundant {
+            pattern: "x.**".to_string(),
+            subsumed_by: "x.*".to_string(),
+        }));
+    }
+
+    #[test]
+    fn builder_supports_a_swapped_delimiter_and_wildcard_alphabet() {
+        let mut matcher: PatternMatcher<u32> = PatternMatcherBuilder::new()
+            .delimiter('/')
+            .single_level_token("+")
+            .multi_level_token("#")
+            .build();
+        matcher.add_pattern("home/+/temperature", 1).unwrap();
+        matcher.add_pattern("home/#", 2).unwrap();
+
+        assert_eq!(matcher.match_topic("home/kitchen/temperature").unwrap().len(), 2);
+        assert_eq!(matcher.match_topic("home/kitchen/humidity").unwrap(), vec![("home/#", &2)]);
+    }
+
+    #[test]
+    fn multi_level_terminal_only_rejects_a_non_final_multi_level_wildcard() {
+        let mut matcher: PatternMatcher<u32> =
+            PatternMatcherBuilder::new().multi_level_terminal_only(true).build();
+
+        let err = matcher.add_pattern("a.**.b", 1).unwrap_err();
+
+        assert_eq!(err, PatternError::NonTerminalMultiLevelWildcard { pattern: "a.**.b".to_string() });
+        assert!(matcher.add_pattern("a.**", 2).is_ok());
+    }
+
+    #[test]
+    fn add_pattern_with_matchers_matches_via_regex_and_predicate_segments() {
+        let mut matcher: PatternMatcher<u32> = PatternMatcher::new();
+        let ticker = matcher.add_regex_matcher("^[A-Z]{1,4}$").unwrap();
+        let short = matcher.add_predicate_matcher(|segment: &str| segment.len() <= 3);
+
+        matcher
+            .add_pattern_with_matchers(
+                &[Segment::Exact("stock".to_string()), Segment::Pred(ticker), Segment::Exact("price".to_string())],
+                1,
+            )
+            .unwrap();
+        matcher
+            .add_pattern_with_matchers(&[Segment::Exact("order".to_string()), Segment::Pred(short)], 2)
+            .unwrap();
+
+        assert_eq!(matcher.match_topic("stock.IBM.price").unwrap(), vec![("stock.<matcher:0>.price", &1)]);
+        assert!(matcher.match_topic("stock.ibm123.price").unwrap().is_empty());
+        assert_eq!(matcher.match_topic("order.42").unwrap(), vec![("order.<matcher:1>", &2)]);
+        assert!(matcher.match_topic("order.too-long").unwrap().is_empty());
+
+        let captures = matcher.match_topic_with_captures("stock.IBM.price").unwrap();
+        assert_eq!(captures.len(), 1);
+        assert_eq!(captures[0].0, "stock.<matcher:0>.price");
+        assert_eq!(*captures[0].1, 1);
+    }
+
+    #[test]
+    fn remove_returns_data_once_then_none_and_ids_are_never_reused() {
+        let mut matcher: PatternMatcher<u32> = PatternMatcher::new();
+        let id = matcher.add_pattern("a.b.c", 1).unwrap();
+
+        assert_eq!(matcher.remove(id), Some(1));
+        assert_eq!(matcher.remove(id), None);
+        assert!(matcher.match_topic("a.b.c").unwrap().is_empty());
+
+        let new_id = matcher.add_pattern("a.b.c", 2).unwrap();
+        assert_ne!(id, new_id, "ids must not be reused after removal");
+    }
+
+    #[test]
+    fn remove_prunes_now_empty_trie_nodes_but_keeps_siblings_matching() {
+        let mut matcher: PatternMatcher<u32> = PatternMatcher::new();
+        let id = matcher.add_pattern("a.b.c", 1).unwrap();
+        matcher.add_pattern("a.b.d", 2).unwrap();
+
+        assert_eq!(matcher.remove(id), Some(1));
+
+        assert!(matcher.match_topic("a.b.c").unwrap().is_empty());
+        assert_eq!(matcher.match_topic("a.b.d").unwrap(), vec![("a.b.d", &2)]);
     }
 }